@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! The module implements a [`Reporter`] decorator that batches events before handing
+//! them to the wrapped reporter.
+//!
+//! Without batching, the interceptor opens a connection and sends one `Envelope` per
+//! process spawn, which is expensive for builds with thousands of executions. The
+//! `BufferingReporter` accumulates events in memory and flushes them to the wrapped
+//! reporter in one [`Reporter::report_all`] call, either when the buffer reaches a size
+//! threshold or when a time threshold elapses, whichever comes first. A final flush
+//! happens when the reporter is dropped, so no buffered event is lost on shutdown.
+
+use crate::intercept::{Reporter, TimedEvent};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Flush the buffer once it holds this many events.
+const FLUSH_SIZE_THRESHOLD: usize = 256;
+/// Flush the buffer at least this often, even if it has not reached the size threshold.
+const FLUSH_TIME_THRESHOLD: Duration = Duration::from_millis(50);
+
+/// Wraps a [`Reporter`] and batches the events reported through it.
+///
+/// The wrapped reporter is shared with a background thread that performs the
+/// time-triggered flush, so it has to be `Send + Sync + 'static`.
+pub struct BufferingReporter<R: Reporter + Send + Sync + 'static> {
+    inner: Arc<R>,
+    buffer: Arc<Mutex<Vec<TimedEvent>>>,
+    shutdown: Arc<AtomicBool>,
+    flush_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl<R: Reporter + Send + Sync + 'static> BufferingReporter<R> {
+    /// Wraps the given reporter with a buffering decorator.
+    pub fn new(inner: R) -> Self {
+        let inner = Arc::new(inner);
+        let buffer = Arc::new(Mutex::new(Vec::with_capacity(FLUSH_SIZE_THRESHOLD)));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let flush_thread = {
+            let inner = inner.clone();
+            let buffer = buffer.clone();
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                while !shutdown.load(Ordering::SeqCst) {
+                    thread::sleep(FLUSH_TIME_THRESHOLD);
+                    Self::flush(&inner, &buffer);
+                }
+            })
+        };
+
+        BufferingReporter {
+            inner,
+            buffer,
+            shutdown,
+            flush_thread: Some(flush_thread),
+        }
+    }
+
+    /// Drains the buffer and hands the batch to the wrapped reporter, if non-empty.
+    fn flush(inner: &Arc<R>, buffer: &Arc<Mutex<Vec<TimedEvent>>>) {
+        let events = {
+            let mut buffer = buffer.lock().unwrap();
+            if buffer.is_empty() {
+                return;
+            }
+            std::mem::take(&mut *buffer)
+        };
+        if let Err(e) = inner.report_all(events) {
+            log::error!("Failed to flush buffered events: {}", e);
+        }
+    }
+}
+
+impl<R: Reporter + Send + Sync + 'static> Reporter for BufferingReporter<R> {
+    fn report_timed(&self, event: TimedEvent) -> anyhow::Result<()> {
+        let should_flush = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.push(event);
+            buffer.len() >= FLUSH_SIZE_THRESHOLD
+        };
+        if should_flush {
+            Self::flush(&self.inner, &self.buffer);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Reporter + Send + Sync + 'static> Drop for BufferingReporter<R> {
+    /// Flushes any remaining buffered events and stops the background flush thread.
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        Self::flush(&self.inner, &self.buffer);
+        if let Some(thread) = self.flush_thread.take() {
+            if thread.join().is_err() {
+                log::error!("Failed to join the flush thread");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intercept::{Event, ProcessId};
+
+    /// Records every event it is asked to report, so tests can inspect what (and when)
+    /// the wrapped reporter actually received.
+    struct RecordingReporter {
+        received: Arc<Mutex<Vec<TimedEvent>>>,
+    }
+
+    impl Reporter for RecordingReporter {
+        fn report_timed(&self, event: TimedEvent) -> anyhow::Result<()> {
+            self.received.lock().unwrap().push(event);
+            Ok(())
+        }
+    }
+
+    fn sample_event() -> Event {
+        Event::Finished {
+            pid: ProcessId(1),
+            exit_code: Some(0),
+            duration_ms: 1,
+        }
+    }
+
+    #[test]
+    fn does_not_flush_before_the_size_threshold() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let reporter = BufferingReporter::new(RecordingReporter {
+            received: received.clone(),
+        });
+
+        for _ in 0..FLUSH_SIZE_THRESHOLD - 1 {
+            reporter.report(sample_event()).unwrap();
+        }
+
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn flushes_once_the_size_threshold_is_reached() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let reporter = BufferingReporter::new(RecordingReporter {
+            received: received.clone(),
+        });
+
+        for _ in 0..FLUSH_SIZE_THRESHOLD {
+            reporter.report(sample_event()).unwrap();
+        }
+
+        assert_eq!(received.lock().unwrap().len(), FLUSH_SIZE_THRESHOLD);
+    }
+
+    #[test]
+    fn flushes_on_the_time_threshold_even_below_the_size_threshold() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let reporter = BufferingReporter::new(RecordingReporter {
+            received: received.clone(),
+        });
+
+        reporter.report(sample_event()).unwrap();
+        thread::sleep(FLUSH_TIME_THRESHOLD * 3);
+
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn flushes_whatever_remains_buffered_on_drop() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let reporter = BufferingReporter::new(RecordingReporter {
+            received: received.clone(),
+        });
+
+        reporter.report(sample_event()).unwrap();
+        drop(reporter);
+
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,333 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! The module implements the intercept reporter and collector over a client-streaming
+//! gRPC call, as an alternative to the hand-rolled protocol in [`crate::intercept::tcp`].
+//! The reporter streams envelopes to the collector; the collector answers with a single
+//! `Ack` once the stream ends, and sends nothing back while it is open.
+//!
+//! Unlike the TCP transport, the gRPC transport is not limited to `127.0.0.1`: the
+//! collector can bind any reachable address, and a reporter can be pointed at a
+//! collector running in another container, or (in principle) on another host. The
+//! wire format is defined in `proto/intercept.proto` and is meant to be compiled by
+//! `build.rs` into the `proto` submodule below via `tonic_build`; that `build.rs`, the
+//! crate's `Cargo.toml` (with the `tonic`/`prost` dependencies), and the
+//! `config::Intercept` field that would pick [`crate::intercept::Transport::Grpc`]
+//! over the TCP default all live at the crate root, outside this module.
+//!
+//! The [`Collector`] and [`Reporter`] traits are synchronous, so this module owns a
+//! small current-thread Tokio runtime to drive the async tonic client and server.
+
+use crate::intercept::{
+    Collector, Envelope, Event, Execution, ProcessId, Reporter, ReporterId, TimedEvent,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender as StdSender;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::channel as tokio_channel;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+/// Generated from `proto/intercept.proto` by `tonic_build` in `build.rs`.
+pub mod proto {
+    tonic::include_proto!("bear.intercept");
+}
+
+use proto::collector_client::CollectorClient;
+use proto::collector_server::{Collector as CollectorRpc, CollectorServer};
+use proto::Ack;
+
+impl From<&Event> for proto::Event {
+    fn from(event: &Event) -> Self {
+        let kind = match event {
+            Event::Started { pid, execution } => proto::event::Kind::Started(proto::Started {
+                pid: Some(proto::ProcessId { pid: pid.0 }),
+                execution: Some(proto::Execution {
+                    executable: execution.executable.display().to_string(),
+                    arguments: execution.arguments.clone(),
+                    working_dir: execution.working_dir.display().to_string(),
+                    environment: execution.environment.clone(),
+                }),
+            }),
+            Event::Finished {
+                pid,
+                exit_code,
+                duration_ms,
+            } => proto::event::Kind::Finished(proto::Finished {
+                pid: Some(proto::ProcessId { pid: pid.0 }),
+                exit_code: *exit_code,
+                duration_ms: *duration_ms,
+            }),
+        };
+        proto::Event { kind: Some(kind) }
+    }
+}
+
+impl From<&Envelope> for proto::Envelope {
+    fn from(envelope: &Envelope) -> Self {
+        proto::Envelope {
+            rid: Some(proto::ReporterId { id: envelope.rid.0 }),
+            timestamp: envelope.timestamp,
+            event: Some(proto::Event::from(&envelope.event)),
+            token: envelope.token.clone(),
+        }
+    }
+}
+
+impl TryFrom<proto::Event> for Event {
+    type Error = anyhow::Error;
+
+    fn try_from(event: proto::Event) -> Result<Self, Self::Error> {
+        match event
+            .kind
+            .ok_or_else(|| anyhow::anyhow!("event is missing its kind"))?
+        {
+            proto::event::Kind::Started(started) => {
+                let pid = started
+                    .pid
+                    .ok_or_else(|| anyhow::anyhow!("started event is missing the process id"))?;
+                let execution = started
+                    .execution
+                    .ok_or_else(|| anyhow::anyhow!("started event is missing the execution"))?;
+                Ok(Event::Started {
+                    pid: ProcessId(pid.pid),
+                    execution: Execution {
+                        executable: execution.executable.into(),
+                        arguments: execution.arguments,
+                        working_dir: execution.working_dir.into(),
+                        environment: execution.environment,
+                    },
+                })
+            }
+            proto::event::Kind::Finished(finished) => {
+                let pid = finished
+                    .pid
+                    .ok_or_else(|| anyhow::anyhow!("finished event is missing the process id"))?;
+                Ok(Event::Finished {
+                    pid: ProcessId(pid.pid),
+                    exit_code: finished.exit_code,
+                    duration_ms: finished.duration_ms,
+                })
+            }
+        }
+    }
+}
+
+impl TryFrom<proto::Envelope> for Envelope {
+    type Error = anyhow::Error;
+
+    fn try_from(envelope: proto::Envelope) -> Result<Self, Self::Error> {
+        let rid = envelope
+            .rid
+            .ok_or_else(|| anyhow::anyhow!("envelope is missing the reporter id"))?;
+        let event = envelope
+            .event
+            .ok_or_else(|| anyhow::anyhow!("envelope is missing the event"))?;
+
+        Ok(Envelope {
+            rid: ReporterId(rid.id),
+            timestamp: envelope.timestamp,
+            event: Event::try_from(event)?,
+            token: envelope.token,
+        })
+    }
+}
+
+/// Forwards decoded envelopes onto the destination channel handed to [`Collector::collect`],
+/// rejecting any envelope that does not carry the expected session token.
+struct CollectorRpcService {
+    destination: StdSender<Envelope>,
+    session_token: String,
+}
+
+#[tonic::async_trait]
+impl CollectorRpc for CollectorRpcService {
+    async fn report(
+        &self,
+        request: Request<Streaming<proto::Envelope>>,
+    ) -> Result<Response<Ack>, Status> {
+        let mut stream = request.into_inner();
+        while let Some(envelope) = stream
+            .message()
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+        {
+            // A malformed or foreign envelope is rejected on its own, the same way the
+            // TCP collector rejects a single bad line: the stream carries other,
+            // legitimate events from this reporter too, and must stay open.
+            let envelope = match Envelope::try_from(envelope) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    log::warn!("Rejecting an undecodable envelope: {}", e);
+                    continue;
+                }
+            };
+            if envelope.token != self.session_token {
+                log::warn!(
+                    "Rejecting envelope from rid={} with an unrecognized session token",
+                    envelope.rid.0
+                );
+                continue;
+            }
+            self.destination
+                .send(envelope)
+                .map_err(|e| Status::internal(e.to_string()))?;
+        }
+        Ok(Response::new(Ack {}))
+    }
+}
+
+/// Collects the events from the reporters over a client-streaming gRPC call.
+pub struct CollectorOnGrpc {
+    runtime: Runtime,
+    address: std::net::SocketAddr,
+    session_token: String,
+    shutdown: AtomicBool,
+}
+
+impl CollectorOnGrpc {
+    /// Creates a new collector bound to the given interface/port.
+    ///
+    /// Unlike [`crate::intercept::tcp::CollectorOnTcp`]'s default, `bind` is not
+    /// restricted to the loopback interface, which allows collecting events from
+    /// reporters running on remote hosts or inside containers. Only envelopes carrying
+    /// `session_token` are forwarded by [`Collector::collect`].
+    pub fn new(bind: &str, session_token: String) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let listener = runtime.block_on(async { tokio::net::TcpListener::bind(bind).await })?;
+        let address = listener.local_addr()?;
+        // The listener is only used to reserve and discover the address; the actual
+        // server binds the same address again when `collect` starts serving.
+        drop(listener);
+        Ok(CollectorOnGrpc {
+            runtime,
+            address,
+            session_token,
+            shutdown: AtomicBool::new(false),
+        })
+    }
+}
+
+impl Collector for CollectorOnGrpc {
+    fn address(&self) -> String {
+        self.address.to_string()
+    }
+
+    fn collect(&self, destination: StdSender<Envelope>) -> anyhow::Result<()> {
+        let address = self.address;
+        let service = CollectorRpcService {
+            destination,
+            session_token: self.session_token.clone(),
+        };
+        self.runtime.block_on(async {
+            Server::builder()
+                .add_service(CollectorServer::new(service))
+                .serve_with_shutdown(address, async {
+                    while !self.shutdown.load(Ordering::SeqCst) {
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    }
+                })
+                .await
+        })?;
+        Ok(())
+    }
+
+    fn stop(&self) -> anyhow::Result<()> {
+        self.shutdown.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Sends the events to the collector over a gRPC client stream.
+///
+/// The client stream is opened lazily on the first reported event and kept open for
+/// the lifetime of the reporter, so the server sees a single `Report` call per reporter.
+///
+/// `sender` and `forwarder` are `Option`s purely so [`Drop`] can close the channel and
+/// then join the forwarding task; they are always `Some` while the reporter is in use.
+pub struct ReporterOnGrpc {
+    rid: ReporterId,
+    sender: Option<tokio::sync::mpsc::Sender<proto::Envelope>>,
+    forwarder: Option<JoinHandle<()>>,
+    runtime: Runtime,
+    session_token: String,
+}
+
+impl ReporterOnGrpc {
+    /// Connects to the collector at the given destination address, tagging every
+    /// reported event with `session_token`.
+    pub fn new(
+        rid: ReporterId,
+        destination: String,
+        session_token: String,
+    ) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let (sender, receiver) = tokio_channel::<proto::Envelope>(256);
+        let forwarder = runtime.spawn({
+            let destination = destination.clone();
+            async move {
+                let endpoint = format!("http://{destination}");
+                match CollectorClient::connect(endpoint).await {
+                    Ok(mut client) => {
+                        let outbound = ReceiverStream::new(receiver);
+                        if let Err(e) = client.report(Request::new(outbound)).await {
+                            log::error!("Failed to stream events to the collector: {}", e);
+                        }
+                    }
+                    Err(e) => log::error!("Failed to connect to the collector: {}", e),
+                }
+            }
+        });
+        Ok(ReporterOnGrpc {
+            rid,
+            sender: Some(sender),
+            forwarder: Some(forwarder),
+            runtime,
+            session_token,
+        })
+    }
+}
+
+impl Reporter for ReporterOnGrpc {
+    fn report_timed(&self, event: TimedEvent) -> anyhow::Result<()> {
+        let envelope = Envelope {
+            rid: self.rid.clone(),
+            timestamp: event.timestamp,
+            event: event.event,
+            token: self.session_token.clone(),
+        };
+        let sender = self
+            .sender
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("reporter used after it was shut down"))?;
+        self.runtime
+            .block_on(sender.send(proto::Envelope::from(&envelope)))?;
+        Ok(())
+    }
+}
+
+impl Drop for ReporterOnGrpc {
+    /// Closes the outbound stream and waits for the forwarding task to finish
+    /// delivering whatever is still in flight.
+    ///
+    /// The forwarding task only makes progress while the current-thread `runtime` is
+    /// being driven, which otherwise only happens incidentally, from inside
+    /// `report_timed`'s `block_on` calls. Without this, dropping `runtime` after the
+    /// last report would tear down the task mid-stream and silently discard any events
+    /// the collector had not yet acknowledged.
+    fn drop(&mut self) {
+        // Dropping the sender closes the channel, which ends the `ReceiverStream` and
+        // lets the forwarding task's `client.report(...)` call return.
+        self.sender.take();
+        if let Some(forwarder) = self.forwarder.take() {
+            if let Err(e) = self.runtime.block_on(forwarder) {
+                log::error!("gRPC forwarding task failed: {}", e);
+            }
+        }
+    }
+}
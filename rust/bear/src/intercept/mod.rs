@@ -10,6 +10,7 @@
 //! the data structures that are used to represent the events.
 
 use crate::intercept::supervise::supervise;
+use crate::intercept::worker::Worker;
 use crate::{args, config};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -17,21 +18,73 @@ use std::path::{Path, PathBuf};
 use std::process::{Command, ExitCode};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
-use std::{env, fmt, thread};
+use std::{env, fmt};
 
+pub mod buffering;
+pub mod grpc;
 pub mod persistence;
 pub mod supervise;
 pub mod tcp;
+pub mod worker;
 
 /// Declare the environment variables used by the intercept mode.
 pub const KEY_DESTINATION: &str = "INTERCEPT_REPORTER_ADDRESS";
 pub const KEY_PRELOAD_PATH: &str = "LD_PRELOAD";
+pub const KEY_SESSION_TOKEN: &str = "INTERCEPT_SESSION_TOKEN";
+
+/// An [`Event`] paired with the instant it was observed.
+///
+/// A reporter decorator like [`buffering::BufferingReporter`] may not hand an event to
+/// the wrapped reporter until well after it occurred, so the emission time has to be
+/// captured up front and carried alongside the event rather than recomputed at
+/// whatever later point it is finally sent.
+#[derive(Debug, Clone)]
+pub struct TimedEvent {
+    pub timestamp: u64,
+    pub event: Event,
+}
 
 /// Represents the remote sink of supervised process events.
 ///
 /// This allows the reporters to send events to a remote collector.
 pub trait Reporter {
-    fn report(&self, event: Event) -> Result<(), anyhow::Error>;
+    /// Reports a single event, stamped with the current time.
+    fn report(&self, event: Event) -> Result<(), anyhow::Error> {
+        self.report_timed(TimedEvent {
+            timestamp: now(),
+            event,
+        })
+    }
+
+    /// Reports a single event, stamped with the given emission time.
+    ///
+    /// This is the primitive implementations provide; [`Reporter::report`] and
+    /// [`Reporter::report_all`] are both built on top of it, so that an emission time
+    /// captured ahead of time (see [`TimedEvent`]) always makes it onto the wire.
+    fn report_timed(&self, event: TimedEvent) -> Result<(), anyhow::Error>;
+
+    /// Reports a batch of already-timestamped events at once.
+    ///
+    /// The default implementation reports the events one by one, preserving the
+    /// behavior of [`Reporter::report_timed`]. Implementations that can send a batch
+    /// of events over the wire in a single message (see [`tcp::ReporterOnTcp`]) should
+    /// override this to avoid the per-event overhead.
+    fn report_all(&self, events: Vec<TimedEvent>) -> Result<(), anyhow::Error> {
+        for event in events {
+            self.report_timed(event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the current time as milliseconds since the Unix epoch.
+///
+/// Used to stamp an [`Envelope`] with the event's emission time.
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
 }
 
 /// Represents the local sink of supervised process events.
@@ -60,12 +113,17 @@ pub trait Collector {
 
 /// Envelope is a wrapper around the event.
 ///
-/// It contains the reporter id, the timestamp of the event and the event itself.
+/// It contains the reporter id, the timestamp of the event, the event itself, and the
+/// session token of the build that produced it. The collector only accepts envelopes
+/// whose token matches its own, so that a collector bound to a reachable address (see
+/// [`Transport`]) does not pick up envelopes from an unrelated build sharing the
+/// network.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Envelope {
     pub rid: ReporterId,
     pub timestamp: u64,
     pub event: Event,
+    pub token: String,
 }
 
 impl fmt::Display for Envelope {
@@ -80,18 +138,45 @@ impl fmt::Display for Envelope {
 
 /// Represent a relevant life cycle event of a process.
 ///
-/// In the current implementation, we only have one event, the `Started` event.
-/// This event is sent when a process is started. It contains the process id
-/// and the execution information.
+/// A process reports a `Started` event when it is spawned, and a `Finished` event
+/// once it exits. Together they let a consumer derive build-profiling information,
+/// like which compilations were slow or which ones failed, on top of the same stream
+/// that feeds the compilation database.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct Event {
-    pub pid: ProcessId,
-    pub execution: Execution,
+pub enum Event {
+    /// Sent when a process is started. Contains the process id and the information
+    /// necessary to reproduce the execution.
+    Started {
+        pid: ProcessId,
+        execution: Execution,
+    },
+    /// Sent when a process exits.
+    ///
+    /// The `exit_code` is `None` when the process was terminated by a signal, in
+    /// which case no exit code is available from the OS.
+    Finished {
+        pid: ProcessId,
+        exit_code: Option<i32>,
+        duration_ms: u64,
+    },
 }
 
 impl fmt::Display for Event {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Event pid={}, execution={}", self.pid.0, self.execution)
+        match self {
+            Event::Started { pid, execution } => {
+                write!(f, "Event::Started pid={}, execution={}", pid.0, execution)
+            }
+            Event::Finished {
+                pid,
+                exit_code,
+                duration_ms,
+            } => write!(
+                f,
+                "Event::Finished pid={}, exit_code={:?}, duration_ms={}",
+                pid.0, exit_code, duration_ms
+            ),
+        }
     }
 }
 
@@ -131,54 +216,107 @@ pub struct ReporterId(pub u64);
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct ProcessId(pub u32);
 
+/// Selects the wire transport used between the reporters and the collector.
+///
+/// The TCP transport defaults to a random loopback port, for same-host builds; the
+/// gRPC transport has no default and always binds the `host:port` it is given. Either
+/// can be bound to a configurable, externally reachable interface/port, which
+/// lets a central `bear` instance collect events from reporters running on remote
+/// workers or inside containers (e.g. a `distcc` style distributed build). A Unix
+/// domain socket is a natural third option for same-host, cross-container setups, but
+/// is not implemented yet.
+///
+/// [`CollectorService::new`] already takes a `&Transport`, so that is the one
+/// switch point a caller needs; picking [`Transport::Grpc`] over the [`Default`] TCP
+/// transport from the user's configuration (e.g. a `config::Intercept` field) is the
+/// responsibility of the crate's configuration layer, outside this module.
+#[derive(Debug, Clone)]
+pub enum Transport {
+    /// The hand-rolled TCP protocol. `bind` defaults to a random loopback port;
+    /// `Some(addr)` binds the given `host:port`, which may be a non-loopback interface.
+    Tcp { bind: Option<String> },
+    /// A client-streaming gRPC call, bound to the given `host:port`.
+    Grpc { bind: String },
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Tcp { bind: None }
+    }
+}
+
+/// Generates a session token unique to this `bear` invocation.
+///
+/// The token is not a cryptographic secret, just a best-effort guard against a
+/// collector bound to a reachable address picking up envelopes from an unrelated
+/// build sharing the same network.
+fn generate_session_token() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+    format!("{:x}-{:x}", std::process::id(), nanos)
+}
+
 /// The service is responsible for collecting the events from the supervised processes.
 ///
-/// The service is implemented as TCP server that listens on a random port on the loopback
-/// interface. The address of the service can be obtained by the `address` method.
+/// The service is backed by one of the [`Transport`]s; by default that is a TCP server
+/// listening on a random port on the loopback interface, but it can be configured to
+/// bind a reachable address instead. The address of the service can be obtained by
+/// the `address` method.
 ///
-/// The service is started in a separate thread to dispatch the events to the consumer.
+/// The service is started on a [`Worker`], which dispatches the events to the consumer.
 /// The consumer is a function that receives the events from the service and processes them.
-/// It also runs in a separate thread. The reason for having two threads is to avoid blocking
+/// It also runs on its own `Worker`. The reason for having two workers is to avoid blocking
 /// the main thread of the application and decouple the collection from the processing.
 pub(crate) struct CollectorService {
     collector: Arc<dyn Collector>,
-    network_thread: Option<thread::JoinHandle<()>>,
-    output_thread: Option<thread::JoinHandle<()>>,
+    transport: Transport,
+    session_token: String,
+    network_worker: Option<Worker>,
+    output_worker: Option<Worker>,
 }
 
 impl CollectorService {
-    /// Creates a new intercept service.
+    /// Creates a new intercept service using the given transport.
     ///
     /// The `consumer` is a function that receives the events and processes them.
-    /// The function is executed in a separate thread.
-    pub fn new<F>(consumer: F) -> anyhow::Result<Self>
+    /// The function is executed on its own worker.
+    ///
+    /// A session token, unique to this invocation, is generated and handed to the
+    /// collector so it only forwards envelopes that belong to this build (see
+    /// [`Envelope::token`]).
+    pub fn new<F>(transport: &Transport, consumer: F) -> anyhow::Result<Self>
     where
         F: FnOnce(Receiver<Envelope>) -> anyhow::Result<()>,
         F: Send + 'static,
     {
-        let collector = tcp::CollectorOnTcp::new()?;
-        let collector_arc = Arc::new(collector);
+        let session_token = generate_session_token();
+        let collector: Arc<dyn Collector> = match transport {
+            Transport::Tcp { bind } => Arc::new(tcp::CollectorOnTcp::new(
+                bind.as_deref(),
+                session_token.clone(),
+            )?),
+            Transport::Grpc { bind } => {
+                Arc::new(grpc::CollectorOnGrpc::new(bind, session_token.clone())?)
+            }
+        };
+        let collector_arc = collector;
         let (sender, receiver) = channel();
 
         let collector_in_thread = collector_arc.clone();
-        let collector_thread = thread::spawn(move || {
-            let result = collector_in_thread.collect(sender);
-            if let Err(e) = result {
-                log::error!("Failed to collect events: {}", e);
-            }
-        });
-        let output_thread = thread::spawn(move || {
-            let result = consumer(receiver);
-            if let Err(e) = result {
-                log::error!("Failed to process events: {}", e);
-            }
+        let network_worker = Worker::spawn("intercept-collector-network", move || {
+            collector_in_thread.collect(sender)
         });
+        let output_worker = Worker::spawn("intercept-collector-output", move || consumer(receiver));
 
         log::debug!("Collector service started at {}", collector_arc.address());
         Ok(CollectorService {
             collector: collector_arc,
-            network_thread: Some(collector_thread),
-            output_thread: Some(output_thread),
+            transport: transport.clone(),
+            session_token,
+            network_worker: Some(network_worker),
+            output_worker: Some(output_worker),
         })
     }
 
@@ -186,18 +324,57 @@ impl CollectorService {
     pub fn address(&self) -> String {
         self.collector.address()
     }
+
+    /// Returns the transport this service was built with.
+    ///
+    /// A caller that opens its own connection to the collector, rather than going
+    /// through a supervised child process (see
+    /// [`InterceptEnvironment::execute_build_command`]), needs this to pick a reporter
+    /// implementation that actually speaks the collector's protocol.
+    pub fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    /// Returns the session token that reporters must present for their envelopes to
+    /// be forwarded by this service.
+    pub fn session_token(&self) -> &str {
+        &self.session_token
+    }
+
+    /// Stops the collector and joins both workers, returning the first failure
+    /// encountered (if any).
+    ///
+    /// This lets callers observe collection/processing failures deterministically,
+    /// unlike [`Drop`], which can only log them.
+    pub fn shutdown(mut self) -> anyhow::Result<()> {
+        self.collector.stop()?;
+        if let Some(worker) = self.network_worker.take() {
+            worker.join()?;
+        }
+        if let Some(worker) = self.output_worker.take() {
+            worker.join()?;
+        }
+        Ok(())
+    }
 }
 
 impl Drop for CollectorService {
-    /// Shuts down the service.
+    /// Shuts down the service on a best-effort basis: errors are logged, not raised,
+    /// since a destructor cannot return them to the caller. Use [`CollectorService::shutdown`]
+    /// when the caller needs to observe a failure.
     fn drop(&mut self) {
-        // TODO: log the shutdown of the service and any errors
-        self.collector.stop().expect("Failed to stop the collector");
-        if let Some(thread) = self.network_thread.take() {
-            thread.join().expect("Failed to join the collector thread");
+        if let Err(e) = self.collector.stop() {
+            log::error!("Failed to stop the collector: {}", e);
         }
-        if let Some(thread) = self.output_thread.take() {
-            thread.join().expect("Failed to join the output thread");
+        if let Some(worker) = self.network_worker.take() {
+            if let Err(e) = worker.join() {
+                log::error!("Collector network worker failed: {}", e);
+            }
+        }
+        if let Some(worker) = self.output_worker.take() {
+            if let Err(e) = worker.join() {
+                log::error!("Collector output worker failed: {}", e);
+            }
         }
     }
 }
@@ -217,11 +394,13 @@ pub(crate) enum InterceptEnvironment {
     Wrapper {
         bin_dir: tempfile::TempDir,
         address: String,
+        token: String,
         collector: CollectorService,
     },
     Preload {
         path: PathBuf,
         address: String,
+        token: String,
         collector: CollectorService,
     },
 }
@@ -234,6 +413,7 @@ impl InterceptEnvironment {
     /// the execution events.
     pub fn new(config: &config::Intercept, collector: CollectorService) -> anyhow::Result<Self> {
         let address = collector.address();
+        let token = collector.session_token().to_string();
         let result = match config {
             config::Intercept::Wrapper {
                 path,
@@ -248,12 +428,14 @@ impl InterceptEnvironment {
                 InterceptEnvironment::Wrapper {
                     bin_dir,
                     address,
+                    token,
                     collector,
                 }
             }
             config::Intercept::Preload { path } => InterceptEnvironment::Preload {
                 path: path.clone(),
                 address,
+                token,
                 collector,
             },
         };
@@ -265,16 +447,36 @@ impl InterceptEnvironment {
     /// The method is blocking and waits for the build command to finish.
     /// The method returns the exit code of the build command. Result failure
     /// indicates that the build command failed to start.
+    ///
+    /// The build command itself is reported through the same `Started`/`Finished`
+    /// events as the processes it spawns, using reporter id zero, so build-profiling
+    /// consumers can see the wall-clock duration of the whole build alongside its
+    /// individual compilations.
     pub fn execute_build_command(&self, input: args::BuildCommand) -> anyhow::Result<ExitCode> {
-        // TODO: record the execution of the build command
-
         let environment = self.environment();
         let process = input.arguments[0].clone();
         let arguments = input.arguments[1..].to_vec();
 
-        let mut child = Command::new(process);
+        // The build command actually runs with its inherited environment overlaid by
+        // `environment` (the vars `.envs()` adds below), so that is what gets
+        // recorded, not just the handful of intercept-mode vars added on top of it.
+        let mut execution_environment: HashMap<String, String> = env::vars().collect();
+        execution_environment.extend(environment.iter().cloned());
+        let execution = Execution {
+            executable: PathBuf::from(&process),
+            arguments: input.arguments.clone(),
+            working_dir: env::current_dir().unwrap_or_default(),
+            environment: execution_environment,
+        };
+        let reporter = self.build_command_reporter()?;
 
-        let exit_status = supervise(child.args(arguments).envs(environment))?;
+        let mut child = Command::new(process);
+        let exit_status = supervise(
+            child.args(arguments).envs(environment),
+            reporter.as_ref(),
+            ProcessId(std::process::id()),
+            execution,
+        )?;
         log::info!("Execution finished with status: {:?}", exit_status);
 
         // The exit code is not always available. When the process is killed by a signal,
@@ -287,6 +489,55 @@ impl InterceptEnvironment {
         Ok(exit_code)
     }
 
+    /// Returns the address of the collector that this environment's children (and the
+    /// build command itself) report their events to.
+    fn address(&self) -> &str {
+        match self {
+            InterceptEnvironment::Wrapper { address, .. } => address,
+            InterceptEnvironment::Preload { address, .. } => address,
+        }
+    }
+
+    /// Returns the session token that this environment's children (and the build
+    /// command itself) must present to the collector.
+    fn token(&self) -> &str {
+        match self {
+            InterceptEnvironment::Wrapper { token, .. } => token,
+            InterceptEnvironment::Preload { token, .. } => token,
+        }
+    }
+
+    /// Returns the collector service backing this environment.
+    fn collector(&self) -> &CollectorService {
+        match self {
+            InterceptEnvironment::Wrapper { collector, .. } => collector,
+            InterceptEnvironment::Preload { collector, .. } => collector,
+        }
+    }
+
+    /// Builds the reporter used to report the build command's own `Started`/`Finished`
+    /// events.
+    ///
+    /// The supervised child processes reach the collector through the wrapper/preload
+    /// executables (which speak whatever protocol `self.address()` implies), but the
+    /// build command's self-report is sent directly from this process, so it has to
+    /// match the collector's actual [`Transport`] instead of assuming TCP.
+    fn build_command_reporter(&self) -> anyhow::Result<Box<dyn Reporter>> {
+        let reporter: Box<dyn Reporter> = match self.collector().transport() {
+            Transport::Tcp { .. } => Box::new(tcp::ReporterOnTcp::new(
+                ReporterId(0),
+                self.address().to_string(),
+                self.token().to_string(),
+            )),
+            Transport::Grpc { .. } => Box::new(grpc::ReporterOnGrpc::new(
+                ReporterId(0),
+                self.address().to_string(),
+                self.token().to_string(),
+            )?),
+        };
+        Ok(reporter)
+    }
+
     /// Returns the environment variables for the intercept environment.
     ///
     /// The environment variables are different for each intercept mode.
@@ -295,7 +546,10 @@ impl InterceptEnvironment {
     fn environment(&self) -> Vec<(String, String)> {
         match self {
             InterceptEnvironment::Wrapper {
-                bin_dir, address, ..
+                bin_dir,
+                address,
+                token,
+                ..
             } => {
                 let path_original = env::var("PATH").unwrap_or_else(|_| String::new());
                 let path_updated = InterceptEnvironment::insert_to_path(
@@ -305,9 +559,15 @@ impl InterceptEnvironment {
                 vec![
                     ("PATH".to_string(), path_updated),
                     (KEY_DESTINATION.to_string(), address.clone()),
+                    (KEY_SESSION_TOKEN.to_string(), token.clone()),
                 ]
             }
-            InterceptEnvironment::Preload { path, address, .. } => {
+            InterceptEnvironment::Preload {
+                path,
+                address,
+                token,
+                ..
+            } => {
                 let path_original = env::var(KEY_PRELOAD_PATH).unwrap_or_else(|_| String::new());
                 let path_updated = InterceptEnvironment::insert_to_path(
                     &path_original,
@@ -316,6 +576,7 @@ impl InterceptEnvironment {
                 vec![
                     (KEY_PRELOAD_PATH.to_string(), path_updated),
                     (KEY_DESTINATION.to_string(), address.clone()),
+                    (KEY_SESSION_TOKEN.to_string(), token.clone()),
                 ]
             }
         }
@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Supervises a child process, reporting its life cycle as intercept events.
+
+use crate::intercept::{Event, Execution, ProcessId, Reporter};
+use std::process::{Command, ExitStatus};
+use std::time::Instant;
+
+/// Spawns `command`, waits for it to exit, and reports its `Started` and `Finished`
+/// events through `reporter`.
+///
+/// The `pid` and `execution` identify the supervised process to the reporter. They are
+/// not necessarily the `command`'s own OS process id: callers may supervise a process
+/// that represents a higher level unit of work, such as the build command itself.
+///
+/// A failure to report the `Finished` event is logged but does not fail the function,
+/// since the child has already exited by that point and its exit status is the
+/// primary thing the caller needs back.
+pub fn supervise(
+    command: &mut Command,
+    reporter: &dyn Reporter,
+    pid: ProcessId,
+    execution: Execution,
+) -> anyhow::Result<ExitStatus> {
+    reporter.report(Event::Started {
+        pid: pid.clone(),
+        execution,
+    })?;
+
+    let start = Instant::now();
+    let mut child = command.spawn()?;
+    let exit_status = child.wait()?;
+    let duration_ms = start.elapsed().as_millis() as u64;
+
+    if let Err(e) = reporter.report(Event::Finished {
+        pid,
+        exit_code: exit_status.code(),
+        duration_ms,
+    }) {
+        log::error!("Failed to report the process outcome: {}", e);
+    }
+
+    Ok(exit_status)
+}
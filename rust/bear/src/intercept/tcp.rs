@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! The module implements the intercept reporter and collector over a plain TCP socket.
+//!
+//! The protocol is deliberately simple: the collector binds a TCP socket and accepts
+//! connections from reporters. Each reporter opens a connection, writes a single line
+//! containing a JSON encoded `Vec<Envelope>`, and closes the connection. A single
+//! reported event is just a batch of one, so the line delimited, always-a-batch
+//! framing keeps the wire format trivial to produce from the preload library (written
+//! in C) while still letting [`crate::intercept::buffering`] amortize the cost of many
+//! short-lived process executions into one connection.
+//!
+//! By default the collector binds a random port on the loopback interface, so only
+//! reporters on the same host can reach it. Binding a non-loopback interface/port
+//! instead (see [`Transport::Tcp`](crate::intercept::Transport::Tcp)) lets reporters
+//! running on remote workers or inside containers report back to it, which is why
+//! every envelope carries a session token that the collector checks before forwarding.
+
+use crate::intercept::{Collector, Envelope, Reporter, ReporterId, TimedEvent};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+
+/// Collects the events from the reporters over a TCP socket.
+pub struct CollectorOnTcp {
+    listener: TcpListener,
+    address: String,
+    session_token: String,
+    shutdown: AtomicBool,
+}
+
+impl CollectorOnTcp {
+    /// Creates a new collector bound to `bind`, or to a random port on the loopback
+    /// interface when `bind` is `None`.
+    ///
+    /// Only envelopes carrying `session_token` are forwarded by [`Collector::collect`].
+    pub fn new(bind: Option<&str>, session_token: String) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(bind.unwrap_or("127.0.0.1:0"))?;
+        let address = listener.local_addr()?.to_string();
+        Ok(CollectorOnTcp {
+            listener,
+            address,
+            session_token,
+            shutdown: AtomicBool::new(false),
+        })
+    }
+}
+
+impl Collector for CollectorOnTcp {
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    fn collect(&self, destination: Sender<Envelope>) -> anyhow::Result<()> {
+        for stream in self.listener.incoming() {
+            if self.shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+            let stream = stream?;
+            let reader = BufReader::new(stream);
+            for line in reader.lines() {
+                // A malformed line is rejected on its own, the same way the gRPC
+                // collector rejects a single bad envelope: other reporters (and other
+                // connections from this one) must not be taken down by one bad line.
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        log::warn!("Rejecting an unreadable line: {}", e);
+                        continue;
+                    }
+                };
+                if line.is_empty() {
+                    continue;
+                }
+                let envelopes: Vec<Envelope> = match serde_json::from_str(&line) {
+                    Ok(envelopes) => envelopes,
+                    Err(e) => {
+                        log::warn!("Rejecting an undecodable line: {}", e);
+                        continue;
+                    }
+                };
+                for envelope in envelopes {
+                    if envelope.token != self.session_token {
+                        log::warn!(
+                            "Rejecting envelope from rid={} with an unrecognized session token",
+                            envelope.rid.0
+                        );
+                        continue;
+                    }
+                    destination.send(envelope)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn stop(&self) -> anyhow::Result<()> {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // The `accept` loop only notices the shutdown flag between connections,
+        // so open (and immediately drop) a connection to unblock it.
+        let _ = TcpStream::connect(&self.address);
+        Ok(())
+    }
+}
+
+/// Sends the events to the collector over a TCP connection.
+///
+/// A new connection is opened for every call: one per event reported on its own, and
+/// one per batch handed to [`Reporter::report_all`]. Reporting events one by one is
+/// wasteful for builds with many short-lived processes, see
+/// [`crate::intercept::buffering`] for a reporter that amortizes this cost by batching
+/// events.
+pub struct ReporterOnTcp {
+    rid: ReporterId,
+    destination: String,
+    session_token: String,
+}
+
+impl ReporterOnTcp {
+    /// Creates a new reporter that sends events, tagged with `session_token`, to the
+    /// given destination address.
+    pub fn new(rid: ReporterId, destination: String, session_token: String) -> Self {
+        ReporterOnTcp {
+            rid,
+            destination,
+            session_token,
+        }
+    }
+}
+
+impl Reporter for ReporterOnTcp {
+    fn report_timed(&self, event: TimedEvent) -> anyhow::Result<()> {
+        self.report_all(vec![event])
+    }
+
+    fn report_all(&self, events: Vec<TimedEvent>) -> anyhow::Result<()> {
+        let envelopes: Vec<Envelope> = events
+            .into_iter()
+            .map(|timed| Envelope {
+                rid: self.rid.clone(),
+                timestamp: timed.timestamp,
+                event: timed.event,
+                token: self.session_token.clone(),
+            })
+            .collect();
+        let line = serde_json::to_string(&envelopes)?;
+        let mut stream = TcpStream::connect(&self.destination)?;
+        writeln!(stream, "{}", line)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A small, panic-safe wrapper around a named background thread.
+//!
+//! A bare `thread::spawn` has two problems for a long-running service like
+//! [`crate::intercept::CollectorService`]: a panic inside the thread poisons any
+//! `.join().expect(...)` call site with an opaque `Box<dyn Any>`, and there is no
+//! single place that decides how shutdown is ordered (signal, then join). `Worker`
+//! catches panics and turns them into a logged `anyhow::Error`, and it gives callers an
+//! explicit, fallible `join`. It does not signal its task to stop: `join` only waits for
+//! the task to return, and the stop signal comes from the caller (see
+//! [`crate::intercept::CollectorService::shutdown`], which stops the collector and then
+//! joins its workers).
+
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+/// A named background thread whose task result (including a panic) is surfaced as an
+/// `anyhow::Result` instead of being lost or turned into a process abort.
+pub struct Worker {
+    name: String,
+    handle: thread::JoinHandle<anyhow::Result<()>>,
+}
+
+impl Worker {
+    /// Spawns `task` on a new thread named `name`.
+    ///
+    /// A panic inside `task` is caught and logged, and turned into an `Err` so that
+    /// [`Worker::join`] never itself panics.
+    pub fn spawn<F>(name: impl Into<String>, task: F) -> Self
+    where
+        F: FnOnce() -> anyhow::Result<()> + Send + 'static,
+    {
+        let name = name.into();
+        let handle = {
+            let name = name.clone();
+            thread::Builder::new()
+                .name(name.clone())
+                .spawn(move || match panic::catch_unwind(AssertUnwindSafe(task)) {
+                    Ok(result) => result,
+                    Err(payload) => {
+                        let message = panic_message(&payload);
+                        log::error!("Worker '{}' panicked: {}", name, message);
+                        Err(anyhow::anyhow!("worker '{}' panicked: {}", name, message))
+                    }
+                })
+                .expect("failed to spawn worker thread")
+        };
+        Worker { name, handle }
+    }
+
+    /// Blocks until the worker's task returns, and returns its result.
+    ///
+    /// It is the caller's responsibility to signal the task to stop beforehand, if
+    /// the task does not terminate on its own.
+    pub fn join(self) -> anyhow::Result<()> {
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(_) => anyhow::bail!("Worker '{}' panicked while joining", self.name),
+        }
+    }
+}
+
+/// Extracts a human readable message out of a panic payload.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}